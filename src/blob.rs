@@ -1,21 +1,45 @@
 use std::{
     alloc::{self, Layout},
+    fs::File,
     ops::{Deref, DerefMut},
+    os::windows::io::AsRawHandle as _,
     ptr::NonNull,
     slice,
     sync::LazyLock,
 };
 
-use eyre::{OptionExt as _, Result};
+use eyre::{Context as _, OptionExt as _, Result};
 use stable_deref_trait::StableDeref;
-use windows::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO};
+use windows::Win32::{
+    Foundation::{CloseHandle, HANDLE},
+    System::{
+        Memory::{
+            CreateFileMappingW, FILE_MAP_READ, MEM_COMMIT, MEM_RELEASE, MEMORY_MAPPED_VIEW_ADDRESS,
+            MapViewOfFile, PAGE_READONLY, PAGE_READWRITE, MEM_RESERVE, UnmapViewOfFile,
+            VirtualAlloc, VirtualFree,
+        },
+        SystemInformation::{GetSystemInfo, SYSTEM_INFO},
+    },
+};
+
+/// How a [`Blob`]'s memory was obtained, and therefore how it must be freed.
+enum Backing {
+    /// Allocated on the heap with the global allocator.
+    Heap(Layout),
+    /// Reserved and committed with `VirtualAlloc`, e.g. for [`Dll::map_image`](crate::Dll::map_image)
+    /// where the pages need to support later protection changes.
+    Virtual,
+    /// A read-only view of a file, obtained with `CreateFileMappingW`/`MapViewOfFile`.
+    Mapped,
+}
 
 pub struct Blob {
-    layout: Layout,
+    backing: Backing,
+    size: usize,
     data: NonNull<u8>,
 }
 
-// Safety: Blob is heap allocated
+// Safety: Blob's backing memory (heap, VirtualAlloc, or a mapped view) is stable for its lifetime
 unsafe impl StableDeref for Blob {}
 
 impl Blob {
@@ -34,12 +58,62 @@ impl Blob {
         let ptr = unsafe { alloc::alloc_zeroed(layout) };
 
         let this = Self {
-            layout,
+            backing: Backing::Heap(layout),
+            size,
             data: NonNull::new(ptr).ok_or_eyre("failed to alloc blob")?,
         };
 
         Ok(this)
     }
+
+    /// Reserve and commit a zeroed region of `size` bytes with `VirtualAlloc`.
+    ///
+    /// Unlike [`Blob::new_zeroed`], the pages backing this blob can later have their
+    /// protection changed with `VirtualProtect`, which is what a reflectively mapped
+    /// PE image needs once its sections are laid out.
+    pub fn new_image(size: usize) -> Result<Self> {
+        let ptr = unsafe { VirtualAlloc(None, size, MEM_COMMIT | MEM_RESERVE, PAGE_READWRITE) };
+
+        let data = NonNull::new(ptr.cast::<u8>()).ok_or_eyre("failed to reserve image memory")?;
+
+        let this = Self {
+            backing: Backing::Virtual,
+            size,
+            data,
+        };
+
+        Ok(this)
+    }
+
+    /// Map `file` read-only with `CreateFileMappingW`/`MapViewOfFile` instead of reading it
+    /// into a heap allocation, so parsing a large plugin DLL (e.g. in [`Dll::new`](crate::Dll::new))
+    /// doesn't double its memory use. `MapViewOfFile` already returns a view aligned to
+    /// `dwAllocationGranularity`, the same alignment [`Blob::new_zeroed`] uses.
+    pub fn from_file_mapping(file: File) -> Result<Self> {
+        let size = file.metadata()?.len() as usize;
+
+        let handle = HANDLE(file.as_raw_handle());
+
+        let mapping = unsafe { CreateFileMappingW(handle, None, PAGE_READONLY, 0, 0, None) }
+            .context("failed to create file mapping")?;
+
+        let view = unsafe { MapViewOfFile(mapping, FILE_MAP_READ, 0, 0, 0) };
+
+        // The mapping object keeps the view valid after its handle is closed
+        unsafe {
+            _ = CloseHandle(mapping);
+        }
+
+        let data = NonNull::new(view.Value.cast::<u8>()).ok_or_eyre("failed to map file")?;
+
+        let this = Self {
+            backing: Backing::Mapped,
+            size,
+            data,
+        };
+
+        Ok(this)
+    }
 }
 
 impl AsRef<[u8]> for Blob {
@@ -52,22 +126,33 @@ impl Deref for Blob {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        // SAFETY: Data exists, is valid, has been initialized by alloc zeroed
-        unsafe { slice::from_raw_parts(self.data.as_ptr(), self.layout.size()) }
+        // SAFETY: Data exists, is valid, and is initialized for all backings
+        unsafe { slice::from_raw_parts(self.data.as_ptr(), self.size) }
     }
 }
 
 impl DerefMut for Blob {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        // SAFETY: Data exists, is valid, has been initialized by alloc zeroed
-        unsafe { slice::from_raw_parts_mut(self.data.as_ptr(), self.layout.size()) }
+        // SAFETY: Data exists, is valid, and is initialized for all backings
+        unsafe { slice::from_raw_parts_mut(self.data.as_ptr(), self.size) }
     }
 }
 
 impl Drop for Blob {
     fn drop(&mut self) {
-        unsafe {
-            alloc::dealloc(self.data.as_ptr(), self.layout);
+        match self.backing {
+            Backing::Heap(layout) => unsafe {
+                alloc::dealloc(self.data.as_ptr(), layout);
+            },
+            Backing::Virtual => unsafe {
+                // MEM_RELEASE requires the size to be zero; it frees the whole reservation
+                _ = VirtualFree(self.data.as_ptr().cast(), 0, MEM_RELEASE);
+            },
+            Backing::Mapped => unsafe {
+                _ = UnmapViewOfFile(MEMORY_MAPPED_VIEW_ADDRESS {
+                    Value: self.data.as_ptr().cast(),
+                });
+            },
         }
     }
 }
@@ -1,10 +1,11 @@
 use std::{
     ffi::{OsString, c_char},
+    mem,
     os::windows::prelude::OsStringExt as _,
     ptr, slice,
 };
 
-use crate::{PluginData, Version};
+use crate::{PLUGIN_ON_MESSAGE, PluginData, PluginMessage, Version};
 
 /// Get a plugin's data
 ///
@@ -71,6 +72,83 @@ extern "C" fn get_plugin_version(data: &PluginData) -> &Version {
     &data.plugin_ref().version
 }
 
+/// Send a message to a plugin's [`PluginMessage`] handler, if it has one.
+///
+/// The plugin is reflectively mapped once and the mapping is retained on `data` (see
+/// [`PluginData::mapped_image`]), so repeated messages reuse the same image instead of
+/// rebuilding one per call. That image never runs `DllMain`, CRT startup, or TLS callbacks,
+/// so a handler depending on any of that having run first may misbehave.
+///
+/// Returns the handler's own return value, or -1 if the plugin doesn't export
+/// [`PLUGIN_ON_MESSAGE`] (see [`PluginData::has_message_handler`]), or -2 if the plugin
+/// couldn't be mapped to call into.
+///
+/// # Safety
+/// `data` must be a pointer to a valid instance of PluginData, and `msg` must point to a
+/// valid, initialized `PluginMessage`.
+#[unsafe(no_mangle)]
+unsafe extern "C" fn send_plugin_message(data: &PluginData, msg: *const PluginMessage) -> i32 {
+    let Some(rva) = data.dll().symbol_rva(PLUGIN_ON_MESSAGE) else {
+        return -1;
+    };
+
+    let Ok(image) = data.mapped_image() else {
+        return -2;
+    };
+
+    let handler_addr = image.base() as usize + rva as usize;
+    let handler: unsafe extern "C" fn(*const PluginMessage) -> i32 =
+        unsafe { mem::transmute(handler_addr) };
+
+    unsafe { handler(msg) }
+}
+
+/// Get every well-known plugin's data exported by a DLL (`PLUGIN_DATA`, `PLUGIN_DATA_1`, ...;
+/// see [`PluginData::all`]).
+///
+/// Takes in a path to the dll, encoded as UTF16, with length `len`. On success, writes a
+/// heap-allocated array of `*const PluginData` to `*out` and returns its length. Returns 0
+/// and sets `*out` to null if the file couldn't be opened, parsed, or exports no known
+/// plugin data. Free the result with [`free_plugin_data_array`].
+///
+/// # Safety
+/// `len` must be the correct number of u16 elements in `dll` (not bytes), and `out` must be
+/// a valid pointer to write a `*const *const PluginData` into.
+#[unsafe(no_mangle)]
+unsafe extern "C" fn get_all_plugin_data(
+    dll: *const u16,
+    len: usize,
+    out: *mut *const *const PluginData,
+) -> usize {
+    let slice = unsafe { slice::from_raw_parts(dll, len) };
+    let dll = OsString::from_wide(slice);
+
+    let plugins = dll
+        .to_str()
+        .and_then(|path| PluginData::all(path).ok())
+        .filter(|plugins| !plugins.is_empty());
+
+    let Some(plugins) = plugins else {
+        unsafe {
+            *out = ptr::null();
+        }
+        return 0;
+    };
+
+    let count = plugins.len();
+
+    let ptrs: Box<[*const PluginData]> = plugins
+        .into_iter()
+        .map(|data| Box::into_raw(Box::new(data)) as *const PluginData)
+        .collect();
+
+    unsafe {
+        *out = Box::into_raw(ptrs) as *const *const PluginData;
+    }
+
+    count
+}
+
 /// Free the memory used by PluginData.
 ///
 /// # Safety
@@ -82,3 +160,21 @@ extern "C" fn free_plugin_data(data: *const PluginData) {
         drop(data);
     }
 }
+
+/// Free an array returned by [`get_all_plugin_data`], along with each `PluginData` it points to.
+///
+/// # Safety
+/// `ptr` and `len` must be exactly the values [`get_all_plugin_data`] wrote to `out` and
+/// returned.
+#[unsafe(no_mangle)]
+unsafe extern "C" fn free_plugin_data_array(ptr: *const *const PluginData, len: usize) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let boxed = unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr.cast_mut(), len)) };
+
+    for data in boxed.iter() {
+        free_plugin_data(*data);
+    }
+}
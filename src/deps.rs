@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use crate::{PluginData, Version};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DependencyError {
+    #[error("dependency cycle detected")]
+    Cycle,
+    #[error("dependency table for \"{0}\" is corrupt")]
+    Corrupt(String),
+    #[error("plugin \"{0}\" depends on \"{1}\", which isn't present")]
+    Missing(String, String),
+    #[error("plugin \"{0}\" requires \"{1}\" >= {2:?}, but the loaded version is older")]
+    TooOld(String, String, Version),
+}
+
+/// Topologically sort `plugins` by their declared dependencies (see `declare_plugin!`'s
+/// `deps = [...]` form) so that every plugin appears after everything it needs.
+///
+/// Returns indices into `plugins` in load order, or an error if a dependency is missing,
+/// too old, or the dependency graph contains a cycle.
+pub fn resolve_load_order(plugins: &[PluginData]) -> Result<Vec<usize>, DependencyError> {
+    let entries = plugins
+        .iter()
+        .map(|data| {
+            let plugin = data.plugin();
+            let name = &*plugin.name;
+
+            let deps = data
+                .dependencies()
+                .ok_or_else(|| DependencyError::Corrupt(name.to_string()))?;
+            let deps: Vec<(&str, Version)> = deps
+                .iter()
+                .map(|dep| (&*dep.name, dep.min_version))
+                .collect();
+
+            Ok((name, plugin.version, deps))
+        })
+        .collect::<Result<Vec<_>, DependencyError>>()?;
+
+    resolve_order(&entries)
+}
+
+/// Core of [`resolve_load_order`], decoupled from `PluginData` so the graph algorithm can be
+/// unit tested with hand-built graphs instead of real plugin DLLs. Each entry is
+/// `(name, version, deps)`, where `deps` lists the `(name, min_version)` pairs that entry
+/// depends on.
+fn resolve_order(entries: &[(&str, Version, Vec<(&str, Version)>)]) -> Result<Vec<usize>, DependencyError> {
+    let by_name: HashMap<&str, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, (name, ..))| (*name, i))
+        .collect();
+
+    // needed[i] holds the indices of the entries that entry `i` depends on
+    let mut needed: Vec<Vec<usize>> = Vec::with_capacity(entries.len());
+
+    for (name, _, deps) in entries {
+        let mut row = Vec::with_capacity(deps.len());
+
+        for (dep_name, min_version) in deps {
+            let Some(&idx) = by_name.get(dep_name) else {
+                return Err(DependencyError::Missing(name.to_string(), dep_name.to_string()));
+            };
+
+            let installed = entries[idx].1;
+            if installed < *min_version {
+                return Err(DependencyError::TooOld(
+                    name.to_string(),
+                    dep_name.to_string(),
+                    *min_version,
+                ));
+            }
+
+            row.push(idx);
+        }
+
+        needed.push(row);
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unvisited,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        i: usize,
+        needed: &[Vec<usize>],
+        state: &mut [State],
+        order: &mut Vec<usize>,
+    ) -> Result<(), DependencyError> {
+        match state[i] {
+            State::Done => return Ok(()),
+            State::Visiting => return Err(DependencyError::Cycle),
+            State::Unvisited => {}
+        }
+
+        state[i] = State::Visiting;
+
+        for &dep in &needed[i] {
+            visit(dep, needed, state, order)?;
+        }
+
+        state[i] = State::Done;
+        order.push(i);
+
+        Ok(())
+    }
+
+    let mut state = vec![State::Unvisited; entries.len()];
+    let mut order = Vec::with_capacity(entries.len());
+
+    for i in 0..entries.len() {
+        visit(i, &needed, &mut state, &mut order)?;
+    }
+
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(major: u16, minor: u16, patch: u16) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    #[test]
+    fn valid_order_respects_dependencies() {
+        let entries = vec![
+            ("a", v(1, 0, 0), vec![("b", v(1, 0, 0))]),
+            ("b", v(1, 0, 0), vec![]),
+            ("c", v(1, 0, 0), vec![("a", v(1, 0, 0)), ("b", v(1, 0, 0))]),
+        ];
+
+        let order = resolve_order(&entries).unwrap();
+        let pos = |name: &str| order.iter().position(|&i| entries[i].0 == name).unwrap();
+
+        assert!(pos("b") < pos("a"));
+        assert!(pos("a") < pos("c"));
+    }
+
+    #[test]
+    fn cycle_is_detected() {
+        let entries = vec![
+            ("a", v(1, 0, 0), vec![("b", v(1, 0, 0))]),
+            ("b", v(1, 0, 0), vec![("a", v(1, 0, 0))]),
+        ];
+
+        assert!(matches!(resolve_order(&entries), Err(DependencyError::Cycle)));
+    }
+
+    #[test]
+    fn missing_dependency_is_reported() {
+        let entries = vec![("a", v(1, 0, 0), vec![("b", v(1, 0, 0))])];
+
+        let err = resolve_order(&entries).unwrap_err();
+        assert!(matches!(err, DependencyError::Missing(name, dep) if name == "a" && dep == "b"));
+    }
+
+    #[test]
+    fn too_old_dependency_is_reported() {
+        let entries = vec![
+            ("a", v(1, 0, 0), vec![("b", v(2, 0, 0))]),
+            ("b", v(1, 0, 0), vec![]),
+        ];
+
+        let err = resolve_order(&entries).unwrap_err();
+        assert!(
+            matches!(err, DependencyError::TooOld(name, dep, min) if name == "a" && dep == "b" && min == v(2, 0, 0))
+        );
+    }
+}
@@ -1,15 +1,45 @@
-use std::{
-    fs::File,
-    io::{self, Read as _},
-    path::Path,
-};
+use std::{ffi::CString, fs::File, io, ops::Deref, path::Path};
 
 use eyre::{Context as _, Report};
 use pelite::pe::{Pe as _, PeFile, Rva, exports::By};
+use windows::{
+    Win32::System::{
+        LibraryLoader::{GetProcAddress, LoadLibraryA},
+        Memory::{
+            PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_PROTECTION_FLAGS,
+            PAGE_READONLY, PAGE_READWRITE, VirtualProtect,
+        },
+    },
+    core::PCSTR,
+};
 use yoke::{Yoke, Yokeable};
 
 use crate::blob::Blob;
 
+// Directory indices into `OptionalHeader.DataDirectory`
+const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+const IMAGE_DIRECTORY_ENTRY_IMPORT: usize = 1;
+const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+
+// `IMAGE_BASE_RELOCATION` entry types, packed into the high nibble of each u16 entry
+const IMAGE_REL_BASED_ABSOLUTE: u16 = 0;
+const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+// High bit of an `IMAGE_THUNK_DATA64` marking an import-by-ordinal
+const IMAGE_ORDINAL_FLAG64: u64 = 0x8000_0000_0000_0000;
+
+// Section characteristics used to derive page protection
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+// Machine type of the host architecture (ARM64EC processes run AMD64 images)
+#[cfg(target_arch = "aarch64")]
+const HOST_MACHINE: u16 = 0xAA64; // IMAGE_FILE_MACHINE_ARM64
+#[cfg(not(target_arch = "aarch64"))]
+const HOST_MACHINE: u16 = 0x8664; // IMAGE_FILE_MACHINE_AMD64
+
 #[derive(Debug, thiserror::Error)]
 pub enum DllError {
     #[error("{0}")]
@@ -18,18 +48,27 @@ pub enum DllError {
     Io(#[from] io::Error),
     #[error("{0}")]
     Pelite(#[from] pelite::Error),
+    #[error("image machine type does not match the host architecture")]
+    ArchMismatch,
+    #[error("base relocation block is malformed or out of bounds")]
+    BadRelocation,
+    #[error("image headers or section layout are malformed or out of bounds")]
+    BadImage,
+    #[error("import directory is malformed or out of bounds")]
+    BadImport,
+    #[error("failed to resolve import \"{0}\"")]
+    UnresolvedImport(String),
 }
 
 pub struct Dll(Yoke<DllRef<'static>, Blob>);
 
 impl Dll {
     pub fn new<P: AsRef<Path>>(dll: P) -> Result<Self, DllError> {
-        let mut file = File::open(dll)?;
-        let size = file.metadata()?.len() as usize;
-
-        let mut blob = Blob::new_zeroed(size)?;
+        let file = File::open(dll)?;
 
-        file.read_exact(&mut blob)?;
+        // Read-only parsing only ever inspects the file, so map it in instead of copying it
+        // onto the heap.
+        let blob = Blob::from_file_mapping(file)?;
 
         let yoke = Yoke::try_attach_to_cart(blob, |data| {
             let file = PeFile::from_bytes(data).context("failed to parse file")?;
@@ -62,10 +101,330 @@ impl Dll {
         *self.0.get()
     }
 
+    /// Scan the export directory's name table for every export whose name starts with
+    /// `prefix` (e.g. a bundled-plugin DLL exporting `PLUGIN_DATA`, `PLUGIN_DATA_1`, ...),
+    /// returning each matching name together with its RVA.
+    pub fn exports_with_prefix(&self, prefix: &str) -> Vec<(String, Rva)> {
+        let file = self.object().file;
+        let mem = self.mem();
+
+        let dir = &file.optional_header().DataDirectory[IMAGE_DIRECTORY_ENTRY_EXPORT];
+        if dir.Size == 0 {
+            return Vec::new();
+        }
+
+        let Ok(dir_offset) = file.rva_to_file_offset(dir.VirtualAddress) else {
+            return Vec::new();
+        };
+        let Some(raw) = mem.get(dir_offset..dir_offset + 40) else {
+            return Vec::new();
+        };
+
+        let number_of_names = u32::from_le_bytes(raw[24..28].try_into().unwrap());
+        let address_of_functions = u32::from_le_bytes(raw[28..32].try_into().unwrap());
+        let address_of_names = u32::from_le_bytes(raw[32..36].try_into().unwrap());
+        let address_of_name_ordinals = u32::from_le_bytes(raw[36..40].try_into().unwrap());
+
+        let mut out = Vec::new();
+
+        for i in 0..number_of_names {
+            let name_rva = read_rva(mem, file, address_of_names + i * 4);
+            let ordinal = read_u16(mem, file, address_of_name_ordinals + i * 2);
+            let (Some(name_rva), Some(ordinal)) = (name_rva, ordinal) else {
+                continue;
+            };
+
+            let Some(func_rva) = read_rva(mem, file, address_of_functions + ordinal as u32 * 4)
+            else {
+                continue;
+            };
+
+            let Some(name_offset) = file.rva_to_file_offset(name_rva).ok() else {
+                continue;
+            };
+            let Some(name) = read_cstr(mem, name_offset) else {
+                continue;
+            };
+            let Ok(name) = name.into_string() else {
+                continue;
+            };
+
+            if name.starts_with(prefix) {
+                out.push((name, func_rva));
+            }
+        }
+
+        out
+    }
+
     /// Get the backing dll memory
     pub fn mem(&self) -> &[u8] {
         self.0.backing_cart()
     }
+
+    /// Reflectively map this DLL as a runnable module, the way a loader would: headers and
+    /// sections are copied into a fresh image-sized allocation, base relocations are applied,
+    /// imports are resolved by loading each imported DLL, and section protections are set
+    /// to match the PE's own section characteristics.
+    ///
+    /// The image is rejected if its machine type doesn't match the host architecture.
+    pub fn map_image(&self) -> Result<MappedImage, DllError> {
+        let object = self.object();
+        let file = object.file;
+
+        if file.file_header().Machine != HOST_MACHINE {
+            return Err(DllError::ArchMismatch);
+        }
+
+        let opt = file.optional_header();
+        let size_of_image = opt.SizeOfImage as usize;
+        let size_of_headers = opt.SizeOfHeaders as usize;
+        let image_base = opt.ImageBase;
+
+        let src = self.mem();
+        let mut image = Blob::new_image(size_of_image)?;
+
+        // Headers
+        let headers_len = size_of_headers.min(src.len()).min(image.len());
+        image
+            .get_mut(..headers_len)
+            .ok_or(DllError::BadImage)?
+            .copy_from_slice(&src[..headers_len]);
+
+        // Sections
+        for section in file.section_headers() {
+            let dest_start = section.VirtualAddress as usize;
+            let virtual_size = section.VirtualSize as usize;
+            let raw_size = section.SizeOfRawData as usize;
+            let raw_start = section.PointerToRawData as usize;
+
+            let copy_len = raw_size.min(virtual_size);
+
+            let src_bytes = src
+                .get(raw_start..raw_start + copy_len)
+                .ok_or(DllError::BadImage)?;
+            let dest_bytes = image
+                .get_mut(dest_start..dest_start + copy_len)
+                .ok_or(DllError::BadImage)?;
+
+            dest_bytes.copy_from_slice(src_bytes);
+
+            // Zero-fill the remainder of virtual_size beyond what was copied from disk
+            // (already zero from VirtualAlloc, but made explicit; nothing is left to fill when
+            // raw_size >= virtual_size)
+            if virtual_size > copy_len {
+                let tail_start = dest_start + copy_len;
+                let tail_end = dest_start + virtual_size;
+                if let Some(tail) = image.get_mut(tail_start..tail_end) {
+                    tail.fill(0);
+                }
+            }
+        }
+
+        let base = image.as_ptr() as u64;
+        let delta = base.wrapping_sub(image_base);
+
+        if delta != 0 {
+            apply_relocations(&mut image, &opt.DataDirectory[IMAGE_DIRECTORY_ENTRY_BASERELOC], delta)?;
+        }
+
+        resolve_imports(&mut image, &opt.DataDirectory[IMAGE_DIRECTORY_ENTRY_IMPORT])?;
+
+        for section in file.section_headers() {
+            let start = section.VirtualAddress as usize;
+            let len = section.VirtualSize as usize;
+
+            let Some(region) = image.get(start..start + len) else {
+                continue;
+            };
+
+            let protect = section_protection(section.Characteristics);
+            let mut old = PAGE_PROTECTION_FLAGS::default();
+            unsafe {
+                VirtualProtect(region.as_ptr().cast(), region.len(), protect, &mut old)
+                    .map_err(|e| DllError::Report(eyre::eyre!(e)))?;
+            }
+        }
+
+        Ok(MappedImage { image, base })
+    }
+}
+
+/// Walk the `.reloc` directory as a series of `IMAGE_BASE_RELOCATION` blocks and apply `delta`
+/// to every `DIR64`/`HIGHLOW` entry; `ABSOLUTE` entries are padding and are skipped.
+fn apply_relocations(image: &mut [u8], dir: &pelite::image::IMAGE_DATA_DIRECTORY, delta: u64) -> Result<(), DllError> {
+    let dir_start = dir.VirtualAddress as usize;
+    let dir_size = dir.Size as usize;
+
+    if dir_size == 0 {
+        return Ok(());
+    }
+
+    let mut pos = 0usize;
+    while pos + 8 <= dir_size {
+        let block = image
+            .get(dir_start + pos..dir_start + dir_size)
+            .ok_or(DllError::BadRelocation)?;
+
+        let page_rva = u32::from_le_bytes(block[0..4].try_into().unwrap()) as usize;
+        let block_size = u32::from_le_bytes(block[4..8].try_into().unwrap()) as usize;
+
+        // `block.len()` is only `dir_size - pos`; a corrupt block claiming more than that
+        // would panic slicing into it below instead of reporting `BadRelocation`.
+        if block_size < 8 || block_size > dir_size - pos {
+            return Err(DllError::BadRelocation);
+        }
+
+        // Copied out so `image` can be patched while walking the entries
+        let entries = block[8..block_size].to_vec();
+
+        for entry in entries.chunks_exact(2) {
+            let entry = u16::from_le_bytes([entry[0], entry[1]]);
+            let ty = entry >> 12;
+            let offset = (entry & 0x0FFF) as usize;
+
+            match ty {
+                IMAGE_REL_BASED_ABSOLUTE => {}
+                IMAGE_REL_BASED_DIR64 => {
+                    let addr = page_rva + offset;
+                    let slot = image
+                        .get_mut(addr..addr + 8)
+                        .ok_or(DllError::BadRelocation)?;
+                    let value = u64::from_le_bytes(slot.try_into().unwrap());
+                    slot.copy_from_slice(&value.wrapping_add(delta).to_le_bytes());
+                }
+                IMAGE_REL_BASED_HIGHLOW => {
+                    let addr = page_rva + offset;
+                    let slot = image
+                        .get_mut(addr..addr + 4)
+                        .ok_or(DllError::BadRelocation)?;
+                    let value = u32::from_le_bytes(slot.try_into().unwrap());
+                    slot.copy_from_slice(&value.wrapping_add(delta as u32).to_le_bytes());
+                }
+                _ => return Err(DllError::BadRelocation),
+            }
+        }
+
+        pos += block_size;
+    }
+
+    Ok(())
+}
+
+/// Walk the import directory's `IMAGE_IMPORT_DESCRIPTOR` array, loading each named DLL and
+/// patching the IAT thunk with the resolved function address.
+fn resolve_imports(image: &mut [u8], dir: &pelite::image::IMAGE_DATA_DIRECTORY) -> Result<(), DllError> {
+    let dir_start = dir.VirtualAddress as usize;
+
+    if dir.Size == 0 {
+        return Ok(());
+    }
+
+    for index in 0.. {
+        let desc_start = dir_start + index * 20;
+        let desc = image
+            .get(desc_start..desc_start + 20)
+            .ok_or(DllError::BadImport)?;
+
+        let name_rva = u32::from_le_bytes(desc[12..16].try_into().unwrap()) as usize;
+        let first_thunk_rva = u32::from_le_bytes(desc[16..20].try_into().unwrap()) as usize;
+
+        // A zeroed descriptor terminates the array
+        if name_rva == 0 && first_thunk_rva == 0 {
+            break;
+        }
+
+        let name = read_cstr(image, name_rva).ok_or(DllError::BadImport)?;
+
+        let module = unsafe { LoadLibraryA(PCSTR(name.as_ptr().cast())) }.map_err(|_| {
+            DllError::UnresolvedImport(name.to_string_lossy().into_owned())
+        })?;
+
+        for thunk in 0.. {
+            let thunk_start = first_thunk_rva + thunk * 8;
+            let slot = image
+                .get(thunk_start..thunk_start + 8)
+                .ok_or(DllError::BadImport)?;
+            let value = u64::from_le_bytes(slot.try_into().unwrap());
+
+            if value == 0 {
+                break;
+            }
+
+            let proc = if value & IMAGE_ORDINAL_FLAG64 != 0 {
+                let ordinal = (value & 0xFFFF) as u16;
+                unsafe { GetProcAddress(module, PCSTR(ordinal as usize as *const u8)) }
+            } else {
+                // `value` points at an `IMAGE_IMPORT_BY_NAME { Hint: u16, Name: [u8] }`
+                let name = read_cstr(image, value as usize + 2).ok_or(DllError::BadImport)?;
+                unsafe { GetProcAddress(module, PCSTR(name.as_ptr().cast())) }
+            };
+
+            let proc = proc.ok_or(DllError::BadImport)?;
+
+            let slot = image
+                .get_mut(thunk_start..thunk_start + 8)
+                .ok_or(DllError::BadImport)?;
+            slot.copy_from_slice(&(proc as usize as u64).to_le_bytes());
+        }
+    }
+
+    Ok(())
+}
+
+fn read_cstr(image: &[u8], start: usize) -> Option<CString> {
+    let bytes = image.get(start..)?;
+    let end = bytes.iter().position(|&b| b == 0)?;
+    CString::new(&bytes[..end]).ok()
+}
+
+/// Read a little-endian `u32` (an RVA, in every caller) at the RVA `rva` translated
+/// through `file`'s section layout.
+fn read_rva(mem: &[u8], file: PeFile<'_>, rva: Rva) -> Option<Rva> {
+    let offset = file.rva_to_file_offset(rva).ok()?;
+    mem.get(offset..offset + 4)?.try_into().ok().map(u32::from_le_bytes)
+}
+
+/// Read a little-endian `u16` at the RVA `rva` translated through `file`'s section layout.
+fn read_u16(mem: &[u8], file: PeFile<'_>, rva: Rva) -> Option<u16> {
+    let offset = file.rva_to_file_offset(rva).ok()?;
+    mem.get(offset..offset + 2)?.try_into().ok().map(u16::from_le_bytes)
+}
+
+fn section_protection(characteristics: u32) -> PAGE_PROTECTION_FLAGS {
+    let exec = characteristics & IMAGE_SCN_MEM_EXECUTE != 0;
+    let read = characteristics & IMAGE_SCN_MEM_READ != 0;
+    let write = characteristics & IMAGE_SCN_MEM_WRITE != 0;
+
+    match (exec, read, write) {
+        (true, _, true) => PAGE_EXECUTE_READWRITE,
+        (true, true, false) => PAGE_EXECUTE_READ,
+        (true, false, false) => PAGE_EXECUTE,
+        (false, _, true) => PAGE_READWRITE,
+        (false, _, false) => PAGE_READONLY,
+    }
+}
+
+/// A DLL reflectively mapped into its own private image allocation: headers and sections laid
+/// out by RVA, relocations applied, and imports resolved, ready to be inspected or executed.
+pub struct MappedImage {
+    image: Blob,
+    base: u64,
+}
+
+impl MappedImage {
+    /// Base address the image was actually mapped at (after relocation).
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+}
+
+impl Deref for MappedImage {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.image
+    }
 }
 
 #[derive(Copy, Clone, Yokeable)]
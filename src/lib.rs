@@ -1,24 +1,30 @@
 mod blob;
 mod c;
+mod deps;
 mod dll;
+mod loaded;
 mod rstr;
 
 use std::{
+    cell::OnceCell,
     fmt::{self, Debug},
     io::{self, Cursor},
     path::Path,
+    rc::Rc,
 };
 
 use byteorder::{LittleEndian, ReadBytesExt as _};
-use dll::DllError;
+use dll::{DllError, MappedImage};
 use eyre::{Report, Result};
 use konst::{primitive::parse_u16, unwrap_ctx};
 use memchr::memchr;
-use pelite::pe::Pe as _;
+use pelite::pe::{Pe as _, PeFile};
 use yoke::{Yoke, Yokeable};
 
 pub use crate::{
+    deps::{DependencyError, resolve_load_order},
     dll::{Dll, DllRef},
+    loaded::{LoadedPlugin, Symbol},
     rstr::RStr,
 };
 
@@ -27,9 +33,9 @@ compile_error!("32-bit is not supported");
 
 /// The plugin data version
 #[doc(hidden)]
-pub const DATA_VERSION: u64 = 1;
+pub const DATA_VERSION: u64 = 2;
 
-/// Plugin details; DATA_VERSION 1
+/// Plugin details; DATA_VERSION 2
 ///
 /// If you want to identify your own plugin,
 /// export a symbol named PLUGIN_DATA containing
@@ -44,6 +50,15 @@ pub struct Plugin<'a> {
     pub author: RStr<'a>,
     pub description: RStr<'a>,
     pub version: Version,
+    /// Number of [`Dependency`] entries pointed to by `deps_ptr`. Zero for a DATA_VERSION 1
+    /// plugin, or one declared with no dependencies. Use [`PluginData::dependencies`] to
+    /// resolve the actual list.
+    #[doc(hidden)]
+    pub deps_len: u64,
+    /// VA of a `deps_len`-element array of raw [`Dependency`] entries. Only meaningful when
+    /// `deps_len > 0`; use [`PluginData::dependencies`] rather than reading this directly.
+    #[doc(hidden)]
+    pub deps_ptr: u64,
 }
 
 impl<'a> Plugin<'a> {
@@ -67,6 +82,21 @@ impl<'a> Plugin<'a> {
         let minor = data.read_u16::<LittleEndian>().ok()?;
         let patch = data.read_u16::<LittleEndian>().ok()?;
 
+        // DATA_VERSION 1 plugins end here; anything older simply doesn't have the
+        // dependency fields below, which is exactly what this gate protects against.
+        let (deps_len, deps_ptr) = if version >= 2 {
+            // `deps_len`/`deps_ptr` are 8-byte aligned, so the compiler pads 2 bytes after
+            // `patch` to align them; consume that padding explicitly.
+            data.read_u16::<LittleEndian>().ok()?;
+
+            let deps_len = data.read_u64::<LittleEndian>().ok()?;
+            let deps_ptr = data.read_u64::<LittleEndian>().ok()?;
+
+            (deps_len, deps_ptr)
+        } else {
+            (0, 0)
+        };
+
         let this = Self {
             data_ver: version,
             name,
@@ -77,6 +107,8 @@ impl<'a> Plugin<'a> {
                 minor,
                 patch,
             },
+            deps_len,
+            deps_ptr,
         };
 
         Some(this)
@@ -94,7 +126,7 @@ impl Debug for Plugin<'_> {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(C)]
 pub struct Version {
     pub major: u16,
@@ -102,6 +134,14 @@ pub struct Version {
     pub patch: u16,
 }
 
+/// A single dependency declared via `declare_plugin!`'s `deps = [...]` form.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Dependency<'a> {
+    pub name: RStr<'a>,
+    pub min_version: Version,
+}
+
 /// Define a plugin's name, author, and description
 ///
 /// In the crate root file, declare the name, author, and description
@@ -115,10 +155,32 @@ pub struct Version {
 /// ```
 ///
 /// The strings must not contain any null bytes in them
+///
+/// Plugins that declare dependencies on other plugins can add a trailing `deps = [...]` form,
+/// where each entry is `(name, major, minor, patch)` for the minimum required version:
+/// ```rs
+/// declare_plugin!("name", "author", "description", deps = [("other-plugin", 1, 0, 0)]);
+/// ```
 #[macro_export]
 macro_rules! declare_plugin {
     ($name:expr, $author:expr, $desc:expr) => {
+        $crate::declare_plugin!($name, $author, $desc, deps = []);
+    };
+    ($name:expr, $author:expr, $desc:expr, deps = [$(($dep_name:expr, $dep_major:expr, $dep_minor:expr, $dep_patch:expr)),* $(,)?]) => {
         const _: () = {
+            static DEPS: &[$crate::Dependency<'static>] = &[
+                $(
+                    $crate::Dependency {
+                        name: unsafe { $crate::RStr::from_str(concat!($dep_name, "\0")) },
+                        min_version: $crate::Version {
+                            major: $dep_major,
+                            minor: $dep_minor,
+                            patch: $dep_patch,
+                        },
+                    },
+                )*
+            ];
+
             #[unsafe(no_mangle)]
             static PLUGIN_DATA: $crate::Plugin<'static> = $crate::Plugin {
                 data_ver: $crate::DATA_VERSION,
@@ -130,11 +192,63 @@ macro_rules! declare_plugin {
                     minor: $crate::convert_str_to_u16(env!("CARGO_PKG_VERSION_MINOR")),
                     patch: $crate::convert_str_to_u16(env!("CARGO_PKG_VERSION_PATCH")),
                 },
+                deps_len: DEPS.len() as u64,
+                deps_ptr: DEPS.as_ptr() as u64,
             };
         };
     };
 }
 
+/// Export name of the handler installed by [`declare_plugin_handler!`].
+#[doc(hidden)]
+pub const PLUGIN_ON_MESSAGE: &str = "PLUGIN_ON_MESSAGE";
+
+/// A message sent from a host to a plugin's [`declare_plugin_handler!`] handler.
+///
+/// `#[repr(C)]` so the layout is stable across the DLL boundary.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub enum PluginMessage {
+    /// Ask the plugin to reload itself.
+    Reload,
+    /// Ask the plugin to reset to its initial state.
+    Reset,
+    /// A UI-style event. `kind` identifies the event, `payload` is event-specific and may be null.
+    Event { kind: u32, payload: *const u8 },
+}
+
+/// Install a handler for host-sent [`PluginMessage`]s, exported under [`PLUGIN_ON_MESSAGE`].
+///
+/// ```rs
+/// declare_plugin_handler!(|msg: &native_plugin_lib::PluginMessage| {
+///     match msg {
+///         native_plugin_lib::PluginMessage::Reload => { /* ... */ }
+///         _ => {}
+///     }
+///
+///     0
+/// });
+/// ```
+///
+/// Use [`PluginData::has_message_handler`] to feature-detect whether a loaded plugin
+/// exports this handler before sending it a message.
+#[macro_export]
+macro_rules! declare_plugin_handler {
+    ($handler:expr) => {
+        const _: () = {
+            #[unsafe(no_mangle)]
+            extern "C" fn PLUGIN_ON_MESSAGE(msg: *const $crate::PluginMessage) -> i32 {
+                let handler: fn(&$crate::PluginMessage) -> i32 = $handler;
+
+                // Safety: the host is required to pass a pointer to a valid PluginMessage
+                let msg = unsafe { &*msg };
+
+                handler(msg)
+            }
+        };
+    };
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum PluginError {
     #[error("{0}")]
@@ -156,7 +270,8 @@ pub enum PluginError {
 }
 
 pub struct PluginData {
-    plugin: Yoke<Plugin<'static>, Box<Dll>>,
+    plugin: Yoke<Plugin<'static>, Rc<Dll>>,
+    image: OnceCell<MappedImage>,
 }
 
 impl PluginData {
@@ -166,9 +281,23 @@ impl PluginData {
     }
 
     pub fn from_dll(dll: Dll) -> Result<Self, PluginError> {
-        let rva = dll
-            .symbol_rva("PLUGIN_DATA")
-            .ok_or(PluginError::SymbolNotFound)?;
+        Self::from_rc(Rc::new(dll), "PLUGIN_DATA")
+    }
+
+    /// Enumerate every well-known plugin descriptor exported by `path`: the primary
+    /// `PLUGIN_DATA` export, plus any `PLUGIN_DATA_<n>` siblings for a DLL bundling several
+    /// plugins (see [`Dll::exports_with_prefix`]). All entries share the one loaded `Dll`.
+    pub fn all<P: AsRef<Path>>(path: P) -> Result<Vec<Self>, PluginError> {
+        let dll = Rc::new(Dll::new(path)?);
+
+        dll.exports_with_prefix("PLUGIN_DATA")
+            .into_iter()
+            .map(|(name, _)| Self::from_rc(Rc::clone(&dll), &name))
+            .collect()
+    }
+
+    fn from_rc(dll: Rc<Dll>, symbol: &str) -> Result<Self, PluginError> {
+        let rva = dll.symbol_rva(symbol).ok_or(PluginError::SymbolNotFound)?;
 
         let offset = dll.object().file.rva_to_file_offset(rva)?;
 
@@ -192,36 +321,20 @@ impl PluginData {
 
         // Below this line we will handle any future data version changes properly
 
-        let yoke = Yoke::try_attach_to_cart(Box::new(dll), |data| {
+        let yoke = Yoke::try_attach_to_cart(dll, |data| {
             let blob = data.mem();
             let file = data.object().file;
 
-            let data = Plugin::from_raw(&blob[offset..], |ptr| {
-                let rva = file.va_to_rva(ptr).ok()?;
-                let offset = file.rva_to_file_offset(rva).ok()?;
-
-                // just keep scanning until \0. If there is one, we have a null terminator
-                // this returns if \0 was not found
-                let end = memchr(0, blob.get(offset..)?)?;
-
-                // now we have to check for utf8 validity.
-                // make sure to include the null terminator as we need it below
-                let rstr = {
-                    let bytes = blob.get(offset..=offset + end)?;
-                    std::str::from_utf8(bytes).ok()?
-                };
-
-                // Safety: String contains a null terminator
-                //         checked by memchr
-                let rstr = unsafe { RStr::from_str(rstr) };
-                Some(rstr)
-            })
-            .ok_or(PluginError::DataCorrupt)?;
+            let data = Plugin::from_raw(&blob[offset..], |ptr| resolve_rstr(file, blob, ptr))
+                .ok_or(PluginError::DataCorrupt)?;
 
             Ok::<Plugin<'_>, PluginError>(data)
         })?;
 
-        let this = Self { plugin: yoke };
+        let this = Self {
+            plugin: yoke,
+            image: OnceCell::new(),
+        };
 
         Ok(this)
     }
@@ -233,9 +346,156 @@ impl PluginData {
     pub fn dll(&self) -> &Dll {
         self.plugin.backing_cart()
     }
+
+    /// Reflectively map this plugin's image the first time it's needed, and retain the
+    /// mapping for the rest of this `PluginData`'s lifetime so repeated calls (e.g. repeated
+    /// [`PluginMessage`]s) reuse it instead of re-mapping, re-relocating, and re-resolving
+    /// imports every time.
+    ///
+    /// The image produced by [`Dll::map_image`] only lays out headers, sections,
+    /// relocations, and imports the way a loader would — it never runs `DllMain`, CRT
+    /// startup, or TLS callbacks, and no SEH/function tables are registered. A plugin whose
+    /// handler depends on any of that having run first may misbehave or crash when invoked
+    /// through this path; load it with [`LoadedPlugin`] instead if that's a concern.
+    pub(crate) fn mapped_image(&self) -> Result<&MappedImage, DllError> {
+        if let Some(image) = self.image.get() {
+            return Ok(image);
+        }
+
+        let image = self.dll().map_image()?;
+        Ok(self.image.get_or_init(|| image))
+    }
+
+    /// Whether this plugin exports a [`PluginMessage`] handler via [`declare_plugin_handler!`].
+    pub fn has_message_handler(&self) -> bool {
+        self.dll().symbol_exists(PLUGIN_ON_MESSAGE)
+    }
+
+    /// Resolve this plugin's declared dependencies (see `declare_plugin!`'s `deps = [...]` form).
+    ///
+    /// Returns an empty list for a DATA_VERSION 1 plugin, or one declared with no dependencies.
+    /// Returns `None` if the dependency table itself is corrupt.
+    pub fn dependencies(&self) -> Option<Vec<Dependency<'_>>> {
+        let plugin = self.plugin();
+
+        if plugin.deps_len == 0 {
+            return Some(Vec::new());
+        }
+
+        let file = self.dll().object().file;
+        let blob = self.dll().mem();
+
+        let rva = file.va_to_rva(plugin.deps_ptr).ok()?;
+        let offset = file.rva_to_file_offset(rva).ok()?;
+
+        // Wire format of a raw Dependency entry: u64 name ptr, 3x u16 version, u16 alignment
+        // padding (the same padding `Plugin::from_raw` skips before its own deps fields).
+        const ENTRY_SIZE: usize = 16;
+
+        let total = usize::try_from(plugin.deps_len).ok()?.checked_mul(ENTRY_SIZE)?;
+        let bytes = blob.get(offset..offset + total)?;
+
+        let mut cursor = Cursor::new(bytes);
+        let mut deps = Vec::with_capacity(plugin.deps_len as usize);
+
+        for _ in 0..plugin.deps_len {
+            let name_ptr = cursor.read_u64::<LittleEndian>().ok()?;
+            let major = cursor.read_u16::<LittleEndian>().ok()?;
+            let minor = cursor.read_u16::<LittleEndian>().ok()?;
+            let patch = cursor.read_u16::<LittleEndian>().ok()?;
+            cursor.read_u16::<LittleEndian>().ok()?; // alignment padding
+
+            let name = resolve_rstr(file, blob, name_ptr)?;
+
+            deps.push(Dependency {
+                name,
+                min_version: Version {
+                    major,
+                    minor,
+                    patch,
+                },
+            });
+        }
+
+        Some(deps)
+    }
+}
+
+/// Translate a VA embedded in a plugin's own static data into an [`RStr`] pointing directly
+/// at the matching null-terminated bytes in `blob`.
+fn resolve_rstr<'a>(file: PeFile<'a>, blob: &'a [u8], va: u64) -> Option<RStr<'a>> {
+    let rva = file.va_to_rva(va).ok()?;
+    let offset = file.rva_to_file_offset(rva).ok()?;
+
+    // just keep scanning until \0. If there is one, we have a null terminator
+    // this returns if \0 was not found
+    let end = memchr(0, blob.get(offset..)?)?;
+
+    // now we have to check for utf8 validity.
+    // make sure to include the null terminator as we need it below
+    let rstr = {
+        let bytes = blob.get(offset..=offset + end)?;
+        std::str::from_utf8(bytes).ok()?
+    };
+
+    // Safety: String contains a null terminator, checked by memchr
+    Some(unsafe { RStr::from_str(rstr) })
 }
 
 #[doc(hidden)]
 pub const fn convert_str_to_u16(string: &'static str) -> u16 {
     unwrap_ctx!(parse_u16(string))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build the raw wire format `Plugin::from_raw` expects: a `u64` version, three `u64`
+    /// pointer slots, a `Version`, and — for `version >= 2` — the 2-byte alignment padding
+    /// plus `deps_len`/`deps_ptr` described in the comments inside `Plugin::from_raw`.
+    fn encode_header(version: u64, deps: Option<(u64, u64)>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&version.to_le_bytes());
+        buf.extend_from_slice(&1u64.to_le_bytes()); // name ptr
+        buf.extend_from_slice(&2u64.to_le_bytes()); // author ptr
+        buf.extend_from_slice(&3u64.to_le_bytes()); // description ptr
+        buf.extend_from_slice(&1u16.to_le_bytes()); // major
+        buf.extend_from_slice(&0u16.to_le_bytes()); // minor
+        buf.extend_from_slice(&0u16.to_le_bytes()); // patch
+
+        if let Some((deps_len, deps_ptr)) = deps {
+            buf.extend_from_slice(&0u16.to_le_bytes()); // alignment padding
+            buf.extend_from_slice(&deps_len.to_le_bytes());
+            buf.extend_from_slice(&deps_ptr.to_le_bytes());
+        }
+
+        buf
+    }
+
+    fn to_rstr(_ptr: u64) -> Option<RStr<'static>> {
+        Some(unsafe { RStr::from_str("x\0") })
+    }
+
+    #[test]
+    fn from_raw_parses_v2_dependency_fields() {
+        let data = encode_header(2, Some((3, 0xdead_beef)));
+
+        let plugin = Plugin::from_raw(&data, to_rstr).unwrap();
+
+        assert_eq!(plugin.data_ver, 2);
+        assert_eq!(plugin.deps_len, 3);
+        assert_eq!(plugin.deps_ptr, 0xdead_beef);
+    }
+
+    #[test]
+    fn from_raw_v1_has_no_dependency_fields() {
+        let data = encode_header(1, None);
+
+        let plugin = Plugin::from_raw(&data, to_rstr).unwrap();
+
+        assert_eq!(plugin.data_ver, 1);
+        assert_eq!(plugin.deps_len, 0);
+        assert_eq!(plugin.deps_ptr, 0);
+    }
+}
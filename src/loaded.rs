@@ -0,0 +1,110 @@
+use std::{
+    ffi::CString, marker::PhantomData, mem, ops::Deref, os::windows::ffi::OsStrExt as _,
+    path::Path,
+};
+
+use windows::{
+    Win32::{
+        Foundation::HMODULE,
+        System::LibraryLoader::{FreeLibrary, GetProcAddress, LoadLibraryW},
+    },
+    core::{PCSTR, PCWSTR},
+};
+
+use crate::{PluginData, PluginError};
+
+/// Name of the plugin's exported init function, looked up by [`LoadedPlugin::plugin_init`].
+pub const PLUGIN_INIT: &str = "plugin_init";
+/// Name of the plugin's exported shutdown function, looked up by [`LoadedPlugin::plugin_shutdown`].
+pub const PLUGIN_SHUTDOWN: &str = "plugin_shutdown";
+
+/// A plugin DLL that has actually been loaded into the process with `LoadLibraryW`, as opposed
+/// to [`PluginData`], which only maps the file for inspection without running any of its code.
+///
+/// Symbols returned by [`LoadedPlugin::get_fn`] borrow from the loaded module and must not
+/// outlive it; dropping a `LoadedPlugin` unloads the module.
+pub struct LoadedPlugin {
+    data: PluginData,
+    module: HMODULE,
+}
+
+impl LoadedPlugin {
+    /// Load `path` into the process and validate its `PLUGIN_DATA` export.
+    ///
+    /// The export is parsed and its version checked the same way [`PluginData::new`] does
+    /// before the module is ever loaded, so an out-of-date or corrupt plugin never gets to
+    /// run any code.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PluginError> {
+        let path = path.as_ref();
+
+        let data = PluginData::new(path)?;
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        let module = unsafe { LoadLibraryW(PCWSTR(wide.as_ptr())) }
+            .map_err(|e| PluginError::Report(eyre::eyre!(e)))?;
+
+        Ok(Self { data, module })
+    }
+
+    /// Look up an export named `name` and reinterpret it as `T`.
+    ///
+    /// The returned [`Symbol`] borrows from `self`, so the borrow checker (rather than just a
+    /// safety comment) rejects keeping it past this `LoadedPlugin`'s `Drop`.
+    ///
+    /// # Safety
+    /// `T` must be a function pointer type matching the real signature of `name`.
+    pub unsafe fn get_fn<T: Copy>(&self, name: &str) -> Option<Symbol<'_, T>> {
+        assert_eq!(
+            size_of::<T>(),
+            size_of::<usize>(),
+            "T must be a function pointer"
+        );
+
+        let name = CString::new(name).ok()?;
+        let proc = unsafe { GetProcAddress(self.module, PCSTR(name.as_ptr().cast())) }?;
+
+        Some(Symbol {
+            inner: unsafe { mem::transmute_copy(&proc) },
+            _marker: PhantomData,
+        })
+    }
+
+    /// The plugin's `plugin_init` entry point, if exported.
+    pub fn plugin_init(&self) -> Option<Symbol<'_, unsafe extern "C" fn()>> {
+        unsafe { self.get_fn(PLUGIN_INIT) }
+    }
+
+    /// The plugin's `plugin_shutdown` entry point, if exported.
+    pub fn plugin_shutdown(&self) -> Option<Symbol<'_, unsafe extern "C" fn()>> {
+        unsafe { self.get_fn(PLUGIN_SHUTDOWN) }
+    }
+
+    /// The validated plugin metadata.
+    pub fn data(&self) -> &PluginData {
+        &self.data
+    }
+}
+
+impl Drop for LoadedPlugin {
+    fn drop(&mut self) {
+        unsafe {
+            _ = FreeLibrary(self.module);
+        }
+    }
+}
+
+/// A symbol resolved from a [`LoadedPlugin`]'s module, borrowed for as long as the plugin it
+/// came from stays loaded. Unlike a bare function pointer, this can't be kept past the
+/// `LoadedPlugin`'s `Drop`, which is what actually unloads the module.
+pub struct Symbol<'a, T> {
+    inner: T,
+    _marker: PhantomData<&'a LoadedPlugin>,
+}
+
+impl<T> Deref for Symbol<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}